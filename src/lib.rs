@@ -1,6 +1,29 @@
+//! IDX format parsing and serialization.
+//!
+//! By default this crate uses `std::io::{Read, Write}`. Enabling the
+//! `embedded_io` feature (and disabling default features to drop `std`)
+//! makes the crate `no_std`, routing I/O through `embedded_io`'s traits
+//! instead so the parser can run against flash or a network socket on
+//! embedded targets. At least one of `std`/`embedded_io` must be enabled;
+//! when both are, I/O goes through `std::io`.
+//!
+//! The crate always links `alloc`, regardless of which I/O backend is
+//! selected: even [`IdxReader`]'s raw token stream carries a `Vec<usize>` of
+//! dimension sizes in [`IdxEvent::Header`], so there is no allocator-free
+//! tier.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "embedded_io")))]
+compile_error!(
+    "idx-lib requires the `std` or `embedded_io` feature to be enabled, to provide an IdxRead/IdxWrite backend"
+);
+
+extern crate alloc;
+
 pub use ndarray::ArrayD;
+use alloc::vec;
+use alloc::vec::Vec;
 use ndarray::IxDyn;
-use std::io::{Read, Seek};
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub enum IdxData {
@@ -14,7 +37,7 @@ pub enum IdxData {
     Double(f64),
 }
 
-impl std::ops::Add for IdxData {
+impl core::ops::Add for IdxData {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
@@ -79,8 +102,8 @@ impl num_traits::identities::Zero for IdxData {
     }
 }
 
-#[derive(Debug)]
-enum IdxType {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdxType {
     UnsignedByte,
     SignedByte,
     Short,
@@ -89,24 +112,164 @@ enum IdxType {
     Double,
 }
 
-#[derive(Debug, Clone)]
-struct IdxError;
+/// The error type returned by this crate's parsing and serialization
+/// functions, carrying enough context (a byte offset, where relevant) to
+/// diagnose a malformed or truncated IDX stream.
+///
+/// Implemented by hand, rather than derived with `thiserror`, so that it
+/// (and its [`core::fmt::Display`] impl) is available without `std`; the
+/// [`std::error::Error`] impl itself is only provided when the `std`
+/// feature is enabled.
+#[derive(Debug)]
+pub enum IdxError {
+    UnknownTypeCode { code: u8, offset: u64 },
+    /// [`read_idx_as`]'s file declares a perfectly valid type code, just not
+    /// the one `T` requires.
+    TypeCodeMismatch { expected: u8, found: u8, offset: u64 },
+    UnexpectedEof { offset: u64, expected: usize },
+    BadMagic { found: [u8; 2] },
+    /// [`write_idx`] was given an array whose elements don't all carry the
+    /// same [`IdxData`] variant (or that contains an [`IdxData::None`]).
+    VariantMismatch,
+    ShapeMismatch,
+    ElementBudgetExceeded { requested: u128, max: usize },
+    IoFailure,
+}
 
-impl std::fmt::Display for IdxError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Error")
+impl core::fmt::Display for IdxError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::UnknownTypeCode { code, offset } => {
+                write!(f, "unknown IDX type code {code:#04x} at offset {offset}")
+            }
+            Self::TypeCodeMismatch { expected, found, offset } => write!(
+                f,
+                "IDX file declares type code {found:#04x} at offset {offset}, expected {expected:#04x}"
+            ),
+            Self::UnexpectedEof { offset, expected } => write!(
+                f,
+                "unexpected end of input at offset {offset}, expected {expected} more byte(s)"
+            ),
+            Self::BadMagic { found } => {
+                write!(f, "bad IDX magic bytes: expected [0x00, 0x00], found {found:?}")
+            }
+            Self::VariantMismatch => write!(
+                f,
+                "array elements do not all carry the same IdxData variant"
+            ),
+            Self::ShapeMismatch => {
+                write!(f, "array shape does not match the data being encoded or decoded")
+            }
+            Self::ElementBudgetExceeded { requested, max } => write!(
+                f,
+                "declared element count {requested} exceeds the configured budget of {max}"
+            ),
+            Self::IoFailure => write!(f, "I/O error while reading or writing an IDX stream"),
+        }
     }
 }
 
+#[cfg(feature = "std")]
 impl std::error::Error for IdxError {}
 
-fn recurser(
-    idx_source: &mut (impl Read + Seek),
+#[cfg(feature = "std")]
+impl From<std::io::Error> for IdxError {
+    fn from(_: std::io::Error) -> Self {
+        IdxError::IoFailure
+    }
+}
+
+/// Abstracts the byte source a parser reads from, so the same parsing code
+/// runs against `std::io::Read` or, with the `embedded_io` feature,
+/// `embedded_io::Read`.
+///
+/// This trait has the backend's own `Read` trait as its supertrait (rather
+/// than being a freestanding trait with a matching blanket impl) so that `&mut R` is
+/// provably `IdxRead` whenever `R: IdxRead` is only known generically, e.g.
+/// inside `fn foo(src: &mut impl IdxRead)` — the backend's own blanket impl
+/// for `&mut _` then carries the bound through.
+#[cfg(feature = "std")]
+pub trait IdxRead: std::io::Read {
+    // The `()` error is always mapped to `IdxError` at the call site, never
+    // surfaced to a caller.
+    #[allow(clippy::result_unit_err)]
+    fn read_exact_bytes(&mut self, buf: &mut [u8]) -> Result<(), ()> {
+        std::io::Read::read_exact(self, buf).map_err(|_| ())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read> IdxRead for R {}
+
+#[cfg(all(feature = "embedded_io", not(feature = "std")))]
+pub trait IdxRead: embedded_io::Read {
+    #[allow(clippy::result_unit_err)]
+    fn read_exact_bytes(&mut self, buf: &mut [u8]) -> Result<(), ()> {
+        embedded_io::Read::read_exact(self, buf).map_err(|_| ())
+    }
+}
+
+#[cfg(all(feature = "embedded_io", not(feature = "std")))]
+impl<R: embedded_io::Read> IdxRead for R {}
+
+/// Abstracts the byte sink [`write_idx`] writes to, mirroring [`IdxRead`].
+#[cfg(feature = "std")]
+pub trait IdxWrite: std::io::Write {
+    #[allow(clippy::result_unit_err)]
+    fn write_all_bytes(&mut self, buf: &[u8]) -> Result<(), ()> {
+        std::io::Write::write_all(self, buf).map_err(|_| ())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> IdxWrite for W {}
+
+#[cfg(all(feature = "embedded_io", not(feature = "std")))]
+pub trait IdxWrite: embedded_io::Write {
+    #[allow(clippy::result_unit_err)]
+    fn write_all_bytes(&mut self, buf: &[u8]) -> Result<(), ()> {
+        embedded_io::Write::write_all(self, buf).map_err(|_| ())
+    }
+}
+
+#[cfg(all(feature = "embedded_io", not(feature = "std")))]
+impl<W: embedded_io::Write> IdxWrite for W {}
+
+fn write_bytes(dest: &mut impl IdxWrite, buf: &[u8]) -> Result<(), IdxError> {
+    dest.write_all_bytes(buf).map_err(|_| IdxError::IoFailure)
+}
+
+/// Wraps an [`IdxRead`] and tracks how many bytes have been consumed from
+/// it, so that parse errors can report where in the stream they occurred.
+struct OffsetReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: IdxRead> OffsetReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, offset: 0 }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), IdxError> {
+        self.inner
+            .read_exact_bytes(buf)
+            .map_err(|_| IdxError::UnexpectedEof {
+                offset: self.offset,
+                expected: buf.len(),
+            })?;
+        self.offset += buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn recurser<R: IdxRead>(
+    idx_source: &mut OffsetReader<R>,
     data: &mut ArrayD<IdxData>,
     dimension_sizes: &[usize],
     data_type: &IdxType,
     past_idxes: &mut Vec<usize>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), IdxError> {
     let current_idx = past_idxes.len();
     // If we are on the last dimension
     if current_idx == dimension_sizes.len() {
@@ -158,52 +321,647 @@ fn recurser(
     }
 }
 
-fn process_dimensions(
-    idx_source: &mut (impl Read + Seek),
+fn process_dimensions<R: IdxRead>(
+    idx_source: &mut OffsetReader<R>,
     data: &mut ArrayD<IdxData>,
     dimension_sizes: &[usize],
     data_type: &IdxType,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), IdxError> {
     recurser(idx_source, data, dimension_sizes, data_type, &mut vec![])
 }
 
-pub fn read_idx(
-    idx_source: &mut (impl Read + Seek),
-) -> Result<ArrayD<IdxData>, Box<dyn std::error::Error>> {
+/// The default cap on the number of elements [`read_idx`] will allocate for,
+/// used by [`read_idx`] itself. See [`read_idx_with_budget`] to configure it.
+pub const DEFAULT_MAX_ELEMENTS: usize = 1 << 30;
+
+/// An IDX header, parsed and budget-checked but not yet interpreted.
+///
+/// Shared by every entry point that reads a header ([`read_idx_with_budget`],
+/// [`read_idx_as`], [`IdxReader`]) so the magic-byte check and the
+/// allocation-budget check can't be skipped by any one of them.
+struct IdxHeader {
+    type_code: u8,
+    type_code_offset: u64,
+    dimension_sizes: Vec<usize>,
+}
+
+/// Validates the magic bytes, reads the type code and dimension sizes, and
+/// rejects a header whose declared element count exceeds `max_elements`
+/// before any caller gets a chance to allocate for it.
+fn parse_header<R: IdxRead>(
+    idx_source: &mut OffsetReader<R>,
+    max_elements: usize,
+) -> Result<IdxHeader, IdxError> {
     // First 2 bytes are always 0
-    idx_source.seek_relative(2)?;
+    let mut magic = [0u8; 2];
+    idx_source.read_exact(&mut magic)?;
+    if magic != [0u8, 0u8] {
+        return Err(IdxError::BadMagic { found: magic });
+    }
 
     // Data in idx is stored in big endian format
     let mut data_type_buf = [0u8; 1];
-    let mut dimension_count_buf = [0u8; 1];
-
     idx_source.read_exact(&mut data_type_buf)?;
-    idx_source.read_exact(&mut dimension_count_buf)?;
+    let type_code_offset = idx_source.offset - 1;
+    let type_code = u8::from_be_bytes(data_type_buf);
 
-    let data_type = match u8::from_be_bytes(data_type_buf) {
-        0x08u8 => IdxType::UnsignedByte,
-        0x09u8 => IdxType::SignedByte,
-        0x0Bu8 => IdxType::Short,
-        0x0Cu8 => IdxType::Int,
-        0x0Du8 => IdxType::Float,
-        0x0Eu8 => IdxType::Double,
-        _ => return Err(IdxError.into()),
-    };
+    let mut dimension_count_buf = [0u8; 1];
+    idx_source.read_exact(&mut dimension_count_buf)?;
     let dimension_count = u8::from_be_bytes(dimension_count_buf);
 
     let mut dimension_sizes = Vec::with_capacity(dimension_count as usize);
-
     for _ in 0..dimension_count {
         let mut dimension_size_buf = [0u8; 4];
         idx_source.read_exact(&mut dimension_size_buf)?;
-        dimension_sizes.push(i32::from_be_bytes(dimension_size_buf) as usize);
+        let dimension_size = i32::from_be_bytes(dimension_size_buf);
+        // A negative size has no sensible meaning and would otherwise
+        // sign-extend into a huge `usize` below; reject it outright rather
+        // than letting it masquerade as an enormous dimension.
+        if dimension_size < 0 {
+            return Err(IdxError::ElementBudgetExceeded {
+                requested: u128::MAX,
+                max: max_elements,
+            });
+        }
+        dimension_sizes.push(dimension_size as usize);
+    }
+
+    // Reject an absurd header before allocating anything for it. Folded
+    // with checked multiplication, since the declared sizes' product can
+    // itself overflow a naive `u128` product on a hostile header.
+    let element_count = dimension_sizes
+        .iter()
+        .try_fold(1u128, |acc, &size| acc.checked_mul(size as u128))
+        .unwrap_or(u128::MAX);
+    if element_count > max_elements as u128 {
+        return Err(IdxError::ElementBudgetExceeded {
+            requested: element_count,
+            max: max_elements,
+        });
     }
 
-    //println!("Data Type: {:#?}\nDimension Count: {}\nDimension Sizes: {:#?}", data_type, dimension_count, dimension_sizes);
+    Ok(IdxHeader {
+        type_code,
+        type_code_offset,
+        dimension_sizes,
+    })
+}
+
+fn idx_type_from_code(type_code: u8, type_code_offset: u64) -> Result<IdxType, IdxError> {
+    match type_code {
+        0x08u8 => Ok(IdxType::UnsignedByte),
+        0x09u8 => Ok(IdxType::SignedByte),
+        0x0Bu8 => Ok(IdxType::Short),
+        0x0Cu8 => Ok(IdxType::Int),
+        0x0Du8 => Ok(IdxType::Float),
+        0x0Eu8 => Ok(IdxType::Double),
+        _ => Err(IdxError::UnknownTypeCode {
+            code: type_code,
+            offset: type_code_offset,
+        }),
+    }
+}
 
-    let mut data: ArrayD<IdxData> = ArrayD::zeros(IxDyn(&dimension_sizes));
+/// Like [`read_idx`], but rejects a header declaring more than
+/// `max_elements` elements before allocating anything, so a hostile or
+/// corrupt header cannot trigger a huge allocation.
+pub fn read_idx_with_budget(
+    idx_source: &mut impl IdxRead,
+    max_elements: usize,
+) -> Result<ArrayD<IdxData>, IdxError> {
+    let mut idx_source = OffsetReader::new(idx_source);
 
-    process_dimensions(idx_source, &mut data, &dimension_sizes, &data_type)?;
+    let header = parse_header(&mut idx_source, max_elements)?;
+    let data_type = idx_type_from_code(header.type_code, header.type_code_offset)?;
+
+    let mut data: ArrayD<IdxData> = ArrayD::zeros(IxDyn(&header.dimension_sizes));
+
+    process_dimensions(
+        &mut idx_source,
+        &mut data,
+        &header.dimension_sizes,
+        &data_type,
+    )?;
 
     Ok(data)
 }
+
+/// Parses an IDX stream into a dynamically-typed, tagged array.
+///
+/// The first two bytes must be `0x00 0x00`; anything else is rejected as
+/// [`IdxError::BadMagic`] rather than silently treated as part of the
+/// header. Allocates with [`DEFAULT_MAX_ELEMENTS`] as the element budget;
+/// use [`read_idx_with_budget`] to configure a different cap.
+pub fn read_idx(idx_source: &mut impl IdxRead) -> Result<ArrayD<IdxData>, IdxError> {
+    read_idx_with_budget(idx_source, DEFAULT_MAX_ELEMENTS)
+}
+
+#[cfg(feature = "std")]
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+#[cfg(feature = "std")]
+const ZLIB_MAGIC_BYTE: u8 = 0x78;
+
+/// Like [`read_idx`], but transparently decompresses `idx_source` first if it
+/// looks gzip- or zlib-compressed (as the canonical MNIST `.gz` distributions
+/// are), by sniffing the leading bytes before delegating to [`read_idx`].
+///
+/// Requires the `std` feature, since gzip/zlib decompression is provided by
+/// `flate2`, which is itself a `std`-only dependency.
+#[cfg(feature = "std")]
+pub fn read_idx_auto(idx_source: &mut impl std::io::Read) -> Result<ArrayD<IdxData>, IdxError> {
+    let mut magic = [0u8; 2];
+    idx_source
+        .read_exact(&mut magic)
+        .map_err(|_| IdxError::UnexpectedEof {
+            offset: 0,
+            expected: 2,
+        })?;
+    let mut prefixed = {
+        use std::io::Read as _;
+        std::io::Cursor::new(magic).chain(idx_source)
+    };
+
+    if magic == GZIP_MAGIC {
+        let mut decoder = flate2::read::GzDecoder::new(prefixed);
+        read_idx(&mut decoder)
+    } else if magic[0] == ZLIB_MAGIC_BYTE {
+        let mut decoder = flate2::read::ZlibDecoder::new(prefixed);
+        read_idx(&mut decoder)
+    } else {
+        read_idx(&mut prefixed)
+    }
+}
+
+fn write_recurser(
+    dest: &mut impl IdxWrite,
+    data: &ArrayD<IdxData>,
+    dimension_sizes: &[usize],
+    past_idxes: &mut Vec<usize>,
+) -> Result<(), IdxError> {
+    let current_idx = past_idxes.len();
+    // If we are on the last dimension
+    if current_idx == dimension_sizes.len() {
+        // Write the data
+        match &data[&past_idxes[..]] {
+            IdxData::None => return Err(IdxError::VariantMismatch),
+            IdxData::UnsignedByte(val) => write_bytes(dest, &val.to_be_bytes())?,
+            IdxData::SignedByte(val) => write_bytes(dest, &val.to_be_bytes())?,
+            IdxData::Short(val) => write_bytes(dest, &val.to_be_bytes())?,
+            IdxData::Int(val) => write_bytes(dest, &val.to_be_bytes())?,
+            IdxData::Float(val) => write_bytes(dest, &val.to_be_bytes())?,
+            IdxData::Double(val) => write_bytes(dest, &val.to_be_bytes())?,
+        }
+        Ok(())
+    } else {
+        let my_idx = past_idxes.len();
+        past_idxes.push(0);
+        // Not in the final dimension
+        for i in 0..dimension_sizes[current_idx] {
+            past_idxes[my_idx] = i;
+            write_recurser(dest, data, dimension_sizes, past_idxes)?;
+        }
+        // Remember to remove our index
+        past_idxes.pop();
+        Ok(())
+    }
+}
+
+/// Writes `data` out in IDX format, the inverse of [`read_idx`].
+///
+/// Every element of `data` must carry the same [`IdxData`] variant; mixing
+/// variants (or leaving elements as [`IdxData::None`]) is rejected with
+/// [`IdxError::VariantMismatch`]. More than [`u8::MAX`] dimensions is
+/// rejected with [`IdxError::ShapeMismatch`], since IDX encodes the
+/// dimension count as a single byte.
+pub fn write_idx(dest: &mut impl IdxWrite, data: &ArrayD<IdxData>) -> Result<(), IdxError> {
+    let shape = data.shape();
+    if shape.len() > u8::MAX as usize {
+        return Err(IdxError::ShapeMismatch);
+    }
+
+    let mut data_type: Option<IdxType> = None;
+    for el in data.iter() {
+        let el_type = match el {
+            IdxData::None => return Err(IdxError::VariantMismatch),
+            IdxData::UnsignedByte(_) => IdxType::UnsignedByte,
+            IdxData::SignedByte(_) => IdxType::SignedByte,
+            IdxData::Short(_) => IdxType::Short,
+            IdxData::Int(_) => IdxType::Int,
+            IdxData::Float(_) => IdxType::Float,
+            IdxData::Double(_) => IdxType::Double,
+        };
+        match &data_type {
+            None => data_type = Some(el_type),
+            Some(existing) if *existing != el_type => return Err(IdxError::VariantMismatch),
+            Some(_) => {}
+        }
+    }
+    let data_type = data_type.ok_or(IdxError::ShapeMismatch)?;
+
+    let type_code: u8 = match data_type {
+        IdxType::UnsignedByte => 0x08,
+        IdxType::SignedByte => 0x09,
+        IdxType::Short => 0x0B,
+        IdxType::Int => 0x0C,
+        IdxType::Float => 0x0D,
+        IdxType::Double => 0x0E,
+    };
+
+    // First 2 bytes are always 0
+    write_bytes(dest, &[0u8, 0u8])?;
+    write_bytes(dest, &[type_code])?;
+    write_bytes(dest, &[shape.len() as u8])?;
+    for &dimension_size in shape {
+        write_bytes(dest, &(dimension_size as i32).to_be_bytes())?;
+    }
+
+    write_recurser(dest, data, shape, &mut vec![])?;
+
+    Ok(())
+}
+
+/// A single token produced while streaming through an IDX source with
+/// [`IdxReader`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdxEvent {
+    /// The parsed header: the element type and the declared dimension sizes.
+    Header { data_type: IdxType, dims: Vec<usize> },
+    /// One element, emitted in row-major order.
+    Element(IdxData),
+    /// There are no more elements; the stream is exhausted.
+    End,
+}
+
+enum IdxReaderState {
+    Start,
+    Reading {
+        data_type: IdxType,
+        total: usize,
+        index: usize,
+    },
+    Done,
+}
+
+/// A pull-based, SAX-style reader over an IDX source.
+///
+/// Unlike [`read_idx`], which allocates the whole [`ArrayD`] up front,
+/// `IdxReader` yields one [`IdxEvent`] per call to [`IdxReader::next`] and
+/// never holds more than a single element in memory, making it suitable for
+/// gigabyte-scale datasets or batching pipelines. The header is still
+/// validated against an element budget (see [`IdxReader::with_budget`]),
+/// since `dims` is attacker-controlled and callers may use it to size their
+/// own buffers.
+pub struct IdxReader<R: IdxRead> {
+    source: OffsetReader<R>,
+    state: IdxReaderState,
+    max_elements: usize,
+}
+
+impl<R: IdxRead> IdxReader<R> {
+    /// Creates a reader using [`DEFAULT_MAX_ELEMENTS`] as the header's
+    /// element budget. See [`IdxReader::with_budget`] to configure it.
+    pub fn new(source: R) -> Self {
+        Self::with_budget(source, DEFAULT_MAX_ELEMENTS)
+    }
+
+    /// Like [`IdxReader::new`], but rejects a header declaring more than
+    /// `max_elements` elements.
+    pub fn with_budget(source: R, max_elements: usize) -> Self {
+        Self {
+            source: OffsetReader::new(source),
+            state: IdxReaderState::Start,
+            max_elements,
+        }
+    }
+
+    /// Pulls the next token out of the stream, or `Ok(None)` once the
+    /// terminal [`IdxEvent::End`] has already been returned.
+    // Deliberately not `Iterator::next`: this can fail mid-stream, so it
+    // returns `Result<Option<_>, IdxError>` rather than `Option<_>`.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<IdxEvent>, IdxError> {
+        match &mut self.state {
+            IdxReaderState::Start => {
+                let header = parse_header(&mut self.source, self.max_elements)?;
+                let data_type = idx_type_from_code(header.type_code, header.type_code_offset)?;
+                let dims = header.dimension_sizes;
+
+                let total = dims.iter().product();
+                self.state = IdxReaderState::Reading {
+                    data_type,
+                    total,
+                    index: 0,
+                };
+                Ok(Some(IdxEvent::Header { data_type, dims }))
+            }
+            IdxReaderState::Reading {
+                data_type,
+                total,
+                index,
+            } => {
+                if *index >= *total {
+                    self.state = IdxReaderState::Done;
+                    return Ok(Some(IdxEvent::End));
+                }
+
+                let element = match data_type {
+                    IdxType::UnsignedByte => {
+                        let mut buf = [0u8; 1];
+                        self.source.read_exact(&mut buf)?;
+                        IdxData::UnsignedByte(u8::from_be_bytes(buf))
+                    }
+                    IdxType::SignedByte => {
+                        let mut buf = [0u8; 1];
+                        self.source.read_exact(&mut buf)?;
+                        IdxData::SignedByte(i8::from_be_bytes(buf))
+                    }
+                    IdxType::Short => {
+                        let mut buf = [0u8; 2];
+                        self.source.read_exact(&mut buf)?;
+                        IdxData::Short(i16::from_be_bytes(buf))
+                    }
+                    IdxType::Int => {
+                        let mut buf = [0u8; 4];
+                        self.source.read_exact(&mut buf)?;
+                        IdxData::Int(i32::from_be_bytes(buf))
+                    }
+                    IdxType::Float => {
+                        let mut buf = [0u8; 4];
+                        self.source.read_exact(&mut buf)?;
+                        IdxData::Float(f32::from_be_bytes(buf))
+                    }
+                    IdxType::Double => {
+                        let mut buf = [0u8; 8];
+                        self.source.read_exact(&mut buf)?;
+                        IdxData::Double(f64::from_be_bytes(buf))
+                    }
+                };
+                *index += 1;
+                Ok(Some(IdxEvent::Element(element)))
+            }
+            IdxReaderState::Done => Ok(None),
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for u8 {}
+    impl Sealed for i8 {}
+    impl Sealed for i16 {}
+    impl Sealed for i32 {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// A primitive type that an IDX file can declare as its element type.
+///
+/// This trait is sealed: it is only implemented for `u8`, `i8`, `i16`,
+/// `i32`, `f32` and `f64`, matching the type codes IDX defines.
+pub trait IdxElement: sealed::Sealed + num_traits::identities::Zero + Clone {
+    /// The IDX type-code byte this type is declared with.
+    const TYPE_CODE: u8;
+
+    /// Decodes a single big-endian-encoded element.
+    fn from_be_bytes(bytes: &[u8]) -> Self;
+}
+
+impl IdxElement for u8 {
+    const TYPE_CODE: u8 = 0x08;
+
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        u8::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl IdxElement for i8 {
+    const TYPE_CODE: u8 = 0x09;
+
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        i8::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl IdxElement for i16 {
+    const TYPE_CODE: u8 = 0x0B;
+
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        i16::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl IdxElement for i32 {
+    const TYPE_CODE: u8 = 0x0C;
+
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        i32::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl IdxElement for f32 {
+    const TYPE_CODE: u8 = 0x0D;
+
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        f32::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+impl IdxElement for f64 {
+    const TYPE_CODE: u8 = 0x0E;
+
+    fn from_be_bytes(bytes: &[u8]) -> Self {
+        f64::from_be_bytes(bytes.try_into().unwrap())
+    }
+}
+
+/// Like [`read_idx`], but decodes directly into a dense `ArrayD<T>` instead
+/// of the tagged [`IdxData`] enum, avoiding a per-element type tag.
+///
+/// Returns an error if the file's declared type code does not match `T`.
+/// Uses [`DEFAULT_MAX_ELEMENTS`] as the allocation budget, same as
+/// [`read_idx`].
+pub fn read_idx_as<T: IdxElement>(idx_source: &mut impl IdxRead) -> Result<ArrayD<T>, IdxError> {
+    let mut idx_source = OffsetReader::new(idx_source);
+
+    let header = parse_header(&mut idx_source, DEFAULT_MAX_ELEMENTS)?;
+    if header.type_code != T::TYPE_CODE {
+        return Err(IdxError::TypeCodeMismatch {
+            expected: T::TYPE_CODE,
+            found: header.type_code,
+            offset: header.type_code_offset,
+        });
+    }
+
+    let mut data: ArrayD<T> = ArrayD::zeros(IxDyn(&header.dimension_sizes));
+
+    let mut elem_buf = vec![0u8; core::mem::size_of::<T>()];
+    for el in data.iter_mut() {
+        idx_source.read_exact(&mut elem_buf)?;
+        *el = T::from_be_bytes(&elem_buf);
+    }
+
+    Ok(data)
+}
+
+// Exercises `std::io`-backed sources/sinks and `read_idx_auto`'s gzip
+// support, both `std`-only.
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    fn sample() -> ArrayD<IdxData> {
+        let mut data = ArrayD::from_elem(IxDyn(&[2, 3]), IdxData::UnsignedByte(0));
+        for (i, el) in data.iter_mut().enumerate() {
+            *el = IdxData::UnsignedByte(i as u8);
+        }
+        data
+    }
+
+    #[test]
+    fn round_trips_through_write_and_read() {
+        let data = sample();
+        let mut buf = Vec::new();
+        write_idx(&mut buf, &data).unwrap();
+
+        let read_back = read_idx(&mut buf.as_slice()).unwrap();
+        assert_eq!(read_back, data);
+    }
+
+    #[test]
+    fn round_trips_through_read_idx_as() {
+        let data = sample();
+        let mut buf = Vec::new();
+        write_idx(&mut buf, &data).unwrap();
+
+        let read_back = read_idx_as::<u8>(&mut buf.as_slice()).unwrap();
+        assert_eq!(read_back.shape(), data.shape());
+        for (got, want) in read_back.iter().zip(data.iter()) {
+            assert_eq!(IdxData::UnsignedByte(*got), *want);
+        }
+    }
+
+    #[test]
+    fn read_idx_as_rejects_mismatched_type_code() {
+        let data = sample();
+        let mut buf = Vec::new();
+        write_idx(&mut buf, &data).unwrap();
+
+        let err = read_idx_as::<i32>(&mut buf.as_slice()).unwrap_err();
+        assert!(matches!(
+            err,
+            IdxError::TypeCodeMismatch {
+                expected: 0x0C,
+                found: 0x08,
+                offset: 2
+            }
+        ));
+    }
+
+    #[test]
+    fn streams_the_same_tokens_as_read_idx() {
+        let data = sample();
+        let mut buf = Vec::new();
+        write_idx(&mut buf, &data).unwrap();
+
+        let mut reader = IdxReader::new(buf.as_slice());
+        assert_eq!(
+            reader.next().unwrap(),
+            Some(IdxEvent::Header {
+                data_type: IdxType::UnsignedByte,
+                dims: vec![2, 3],
+            })
+        );
+        for el in data.iter() {
+            assert_eq!(reader.next().unwrap(), Some(IdxEvent::Element(el.clone())));
+        }
+        assert_eq!(reader.next().unwrap(), Some(IdxEvent::End));
+        assert_eq!(reader.next().unwrap(), None);
+    }
+
+    #[test]
+    fn read_idx_auto_decompresses_gzip() {
+        use std::io::Write as _;
+
+        let data = sample();
+        let mut raw = Vec::new();
+        write_idx(&mut raw, &data).unwrap();
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let read_back = read_idx_auto(&mut gzipped.as_slice()).unwrap();
+        assert_eq!(read_back, data);
+    }
+
+    #[test]
+    fn read_idx_auto_passes_through_uncompressed_data() {
+        let data = sample();
+        let mut raw = Vec::new();
+        write_idx(&mut raw, &data).unwrap();
+
+        let read_back = read_idx_auto(&mut raw.as_slice()).unwrap();
+        assert_eq!(read_back, data);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let buf = [0xFFu8, 0xFF, 0x08, 0x00];
+        let err = read_idx(&mut buf.as_slice()).unwrap_err();
+        assert!(matches!(err, IdxError::BadMagic { found } if found == [0xFF, 0xFF]));
+    }
+
+    #[test]
+    fn reports_unknown_type_code_at_the_type_code_offset() {
+        let buf = [0x00u8, 0x00, 0xAB, 0x00];
+        let err = read_idx(&mut buf.as_slice()).unwrap_err();
+        assert!(matches!(
+            err,
+            IdxError::UnknownTypeCode { code: 0xAB, offset: 2 }
+        ));
+    }
+
+    #[test]
+    fn rejects_a_header_declaring_too_many_elements() {
+        // Type 0x08 (unsigned byte), 1 dimension of size 10.
+        let buf = [0x00u8, 0x00, 0x08, 0x01, 0x00, 0x00, 0x00, 0x0A];
+        let err = read_idx_with_budget(&mut buf.as_slice(), 5).unwrap_err();
+        assert!(matches!(
+            err,
+            IdxError::ElementBudgetExceeded { requested: 10, max: 5 }
+        ));
+    }
+
+    #[test]
+    fn rejects_dimension_sizes_that_would_overflow_the_element_count() {
+        // Type 0x08 (unsigned byte), 4 dimensions of 0xFFFFFFFF (-1 as i32)
+        // each. Must not panic on overflow and must not wrap into a budget
+        // that looks acceptable.
+        let buf = [
+            0x00u8, 0x00, 0x08, 0x04, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+        ];
+        let err = read_idx(&mut buf.as_slice()).unwrap_err();
+        assert!(matches!(err, IdxError::ElementBudgetExceeded { .. }));
+    }
+
+    #[test]
+    fn write_idx_rejects_mixed_variants() {
+        let mut data = ArrayD::from_elem(IxDyn(&[2]), IdxData::UnsignedByte(0));
+        data[[1]] = IdxData::Int(1);
+
+        let mut buf = Vec::new();
+        let err = write_idx(&mut buf, &data).unwrap_err();
+        assert!(matches!(err, IdxError::VariantMismatch));
+    }
+
+    #[test]
+    fn write_idx_rejects_none_elements() {
+        let data = ArrayD::from_elem(IxDyn(&[2]), IdxData::None);
+
+        let mut buf = Vec::new();
+        let err = write_idx(&mut buf, &data).unwrap_err();
+        assert!(matches!(err, IdxError::VariantMismatch));
+    }
+}